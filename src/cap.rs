@@ -0,0 +1,332 @@
+//! IRCv3 `CAP` negotiation interception.
+//!
+//! Built on top of the `CAP` parsing in `irc`, this tracks per-connection negotiation state so
+//! the proxy can mediate which capabilities pass between client and upstream instead of
+//! tunneling `CAP` blind: stripping capabilities from the advertised `LS` set, and rejecting
+//! disallowed client `REQ`s with a synthesized `NAK`.
+
+use std::collections::HashSet;
+
+use bytes::Bytes;
+
+use irc::Message;
+
+/// The line-length limit this proxy enforces elsewhere (see `ingress::DEFAULT_MAX_LINE`).
+const MAX_LINE: usize = 512;
+
+/// A policy describing which capabilities are allowed to pass through the proxy.
+///
+/// Capabilities are matched on their bare name — the part before any `=value`, since a server may
+/// advertise a cap with a value (e.g. `sasl=PLAIN`) that the client doesn't request back.
+#[derive(Debug, Clone, Default)]
+pub struct CapPolicy {
+    denied: HashSet<Vec<u8>>,
+}
+
+impl CapPolicy {
+    pub fn new() -> CapPolicy {
+        CapPolicy { denied: HashSet::new() }
+    }
+
+    /// Marks `cap` as disallowed: it's stripped out of advertised `LS` lists, and any client
+    /// `REQ` for it is rejected with a `NAK` instead of being forwarded upstream.
+    pub fn deny<C: Into<Vec<u8>>>(&mut self, cap: C) {
+        self.denied.insert(cap.into());
+    }
+
+    fn permits(&self, cap: &[u8]) -> bool {
+        let name = match cap.iter().position(|&b| b == b'=') {
+            Some(i) => &cap[..i],
+            None => cap,
+        };
+
+        !self.denied.contains(name)
+    }
+}
+
+/// A `CAP` message, adapted from a parsed `irc::Message`.
+///
+/// `irc::CapMessage` is a fast, borrowed scanner over a raw line; this is its negotiation-side
+/// counterpart, built from the already-parsed `Message` so it can read the `*` continuation
+/// token that `irc::extract_cap` doesn't expose.
+struct CapMessage {
+    subcommand: Bytes,
+    /// Whether a `*` continuation token was present before the trailing list, indicating a
+    /// multiline `LS`/`LIST` response that more lines will complete.
+    more: bool,
+    trailing: Bytes,
+}
+
+impl CapMessage {
+    fn from_message(msg: &Message, is_server: bool) -> Option<CapMessage> {
+        if &msg.verb[..] != b"CAP" {
+            return None;
+        }
+
+        // servers include a client-identifier parameter (usually "*") before the subcommand
+        let params = if is_server {
+            if msg.params.is_empty() { return None; }
+            &msg.params[1..]
+        } else {
+            &msg.params[..]
+        };
+
+        let subcommand = match params.get(0) {
+            Some(subcommand) => subcommand.clone(),
+            None => return None,
+        };
+        let rest = &params[1..];
+
+        // The cap list is usually the trailing parameter, but IRC framing only requires the
+        // leading `:` when a parameter contains a space — a single capability (e.g. `CAP REQ
+        // sasl`, `CAP * ACK sasl`) is legal without one, and shows up as an ordinary param
+        // instead. Fall back to reconstructing the list from the remaining params in that case.
+        let (more, trailing) = match msg.trailing {
+            Some(ref trailing) => {
+                let more = rest.get(0).map(|p| &p[..] == b"*").unwrap_or(false);
+                (more, trailing.clone())
+            }
+            None => {
+                let more = rest.get(0).map(|p| &p[..] == b"*").unwrap_or(false);
+                let rest = if more { &rest[1..] } else { rest };
+                (more, join_caps(rest))
+            }
+        };
+
+        Some(CapMessage { subcommand: subcommand, more: more, trailing: trailing })
+    }
+}
+
+/// Splits a space-separated capability list into its individual tokens.
+fn split_caps(list: &Bytes) -> Vec<Bytes> {
+    let mut caps = Vec::new();
+    let mut i = 0;
+
+    while i < list.len() {
+        while i < list.len() && list[i] == b' ' { i += 1; }
+        let start = i;
+        while i < list.len() && list[i] != b' ' { i += 1; }
+        if i > start {
+            caps.push(list.slice(start, i));
+        }
+    }
+
+    caps
+}
+
+fn join_caps(caps: &[Bytes]) -> Bytes {
+    let mut out = Vec::new();
+
+    for (i, cap) in caps.iter().enumerate() {
+        if i > 0 { out.push(b' '); }
+        out.extend_from_slice(cap);
+    }
+
+    Bytes::from(out)
+}
+
+/// Reassembles a (possibly filtered) capability list into one or more `CAP * LS` lines, splitting
+/// across multiple multiline responses so that none of them exceed `MAX_LINE`.
+fn reframe_ls(caps: &[Bytes]) -> Vec<Bytes> {
+    const PREFIX: &'static str = "CAP * LS * :";
+    const FINAL_PREFIX: &'static str = "CAP * LS :";
+    let budget = MAX_LINE - PREFIX.len();
+
+    let mut lines = Vec::new();
+    let mut chunk: Vec<Bytes> = Vec::new();
+    let mut chunk_len = 0;
+
+    for cap in caps {
+        let added_len = cap.len() + if chunk.is_empty() { 0 } else { 1 };
+
+        if chunk_len + added_len > budget && !chunk.is_empty() {
+            lines.push(chunk);
+            chunk = Vec::new();
+            chunk_len = 0;
+        }
+
+        chunk_len += cap.len() + if chunk.is_empty() { 0 } else { 1 };
+        chunk.push(cap.clone());
+    }
+    lines.push(chunk);
+
+    let last = lines.len() - 1;
+    lines.into_iter().enumerate().map(|(i, chunk)| {
+        let prefix = if i == last { FINAL_PREFIX } else { PREFIX };
+        let mut out = prefix.as_bytes().to_vec();
+        out.extend_from_slice(&join_caps(&chunk));
+        Bytes::from(out)
+    }).collect()
+}
+
+/// Per-connection IRCv3 `CAP` negotiation state.
+///
+/// Reassembles multiline `CAP * LS` responses from the upstream into the full advertised set
+/// before applying `policy`, and rewrites client `REQ`s, synthesizing a `NAK` reply for anything
+/// `policy` disallows rather than forwarding it upstream.
+pub struct CapNegotiator {
+    policy: CapPolicy,
+    advertised: Vec<Bytes>,
+}
+
+impl CapNegotiator {
+    pub fn new(policy: CapPolicy) -> CapNegotiator {
+        CapNegotiator { policy: policy, advertised: Vec::new() }
+    }
+
+    /// Processes a message read from the upstream server, returning the line(s) to forward to the
+    /// client in its place. A non-`CAP` message, or one this negotiator doesn't rewrite, is
+    /// forwarded as-is.
+    pub fn handle_server_line(&mut self, msg: &Message, raw: &Bytes) -> Vec<Bytes> {
+        let cap = match CapMessage::from_message(msg, true) {
+            Some(cap) => cap,
+            None => return vec![raw.clone()],
+        };
+
+        if &cap.subcommand[..] != b"LS" {
+            return vec![raw.clone()];
+        }
+
+        self.advertised.extend(split_caps(&cap.trailing));
+
+        if cap.more {
+            // wait for the rest of the multiline response before filtering and forwarding
+            return Vec::new();
+        }
+
+        let filtered: Vec<Bytes> = self.advertised.drain(..).filter(|c| self.policy.permits(c)).collect();
+        reframe_ls(&filtered)
+    }
+
+    /// Processes a message read from the client, returning the line(s) to forward to the upstream
+    /// in its place, and the line(s) (if any) to reply to the client directly, bypassing the
+    /// upstream entirely — for example, a synthesized `NAK` for a capability `policy` disallows.
+    pub fn handle_client_line(&mut self, msg: &Message, raw: &Bytes) -> (Vec<Bytes>, Vec<Bytes>) {
+        let cap = match CapMessage::from_message(msg, false) {
+            Some(cap) => cap,
+            None => return (vec![raw.clone()], Vec::new()),
+        };
+
+        if &cap.subcommand[..] != b"REQ" {
+            return (vec![raw.clone()], Vec::new());
+        }
+
+        let requested = split_caps(&cap.trailing);
+        let (allowed, denied): (Vec<Bytes>, Vec<Bytes>) =
+            requested.into_iter().partition(|c| self.policy.permits(c));
+
+        let mut forward = Vec::new();
+        if !allowed.is_empty() {
+            let mut line = b"CAP REQ :".to_vec();
+            line.extend_from_slice(&join_caps(&allowed));
+            forward.push(Bytes::from(line));
+        }
+
+        let mut reply = Vec::new();
+        if !denied.is_empty() {
+            let mut line = b"CAP * NAK :".to_vec();
+            line.extend_from_slice(&join_caps(&denied));
+            reply.push(Bytes::from(line));
+        }
+
+        (forward, reply)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strify(b: &Bytes) -> &str { unsafe { ::std::str::from_utf8_unchecked(b) } }
+
+    fn msg(line: &'static str) -> (Message, Bytes) {
+        let raw = Bytes::from_static(line.as_bytes());
+        (Message::parse(raw.clone()).unwrap(), raw)
+    }
+
+    #[test]
+    fn test_server_ls_single_line_filtered() {
+        let mut policy = CapPolicy::new();
+        policy.deny(b"away-notify".to_vec());
+
+        let mut neg = CapNegotiator::new(policy);
+        let (message, raw) = msg(":irc.example.net CAP * LS :multi-prefix away-notify sasl");
+
+        let out = neg.handle_server_line(&message, &raw);
+        assert_eq!(1, out.len());
+        assert_eq!("CAP * LS :multi-prefix sasl", strify(&out[0]));
+    }
+
+    #[test]
+    fn test_server_ls_multiline_reassembled() {
+        let mut neg = CapNegotiator::new(CapPolicy::new());
+
+        let (first, first_raw) = msg(":irc.example.net CAP * LS * :multi-prefix sasl");
+        assert!(neg.handle_server_line(&first, &first_raw).is_empty());
+
+        let (second, second_raw) = msg(":irc.example.net CAP * LS :away-notify");
+        let out = neg.handle_server_line(&second, &second_raw);
+
+        assert_eq!(1, out.len());
+        assert_eq!("CAP * LS :multi-prefix sasl away-notify", strify(&out[0]));
+    }
+
+    #[test]
+    fn test_client_req_denied_caps_get_nak_not_forwarded() {
+        let mut policy = CapPolicy::new();
+        policy.deny(b"sasl".to_vec());
+
+        let mut neg = CapNegotiator::new(policy);
+        let (message, raw) = msg("CAP REQ :multi-prefix sasl");
+
+        let (forward, reply) = neg.handle_client_line(&message, &raw);
+        assert_eq!(vec!["CAP REQ :multi-prefix"], forward.iter().map(strify).collect::<Vec<_>>());
+        assert_eq!(vec!["CAP * NAK :sasl"], reply.iter().map(strify).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_client_req_single_cap_no_colon() {
+        let mut neg = CapNegotiator::new(CapPolicy::new());
+        let (message, raw) = msg("CAP REQ sasl");
+
+        let (forward, reply) = neg.handle_client_line(&message, &raw);
+        assert_eq!(vec!["CAP REQ :sasl"], forward.iter().map(strify).collect::<Vec<_>>());
+        assert!(reply.is_empty());
+    }
+
+    #[test]
+    fn test_server_ls_multiline_continuation_no_colon() {
+        let mut neg = CapNegotiator::new(CapPolicy::new());
+
+        // A single cap needs no embedded space, so the trailing `*` marker can be an ordinary
+        // param instead of `:`-prefixed — this must still be read as a continuation, and the `*`
+        // itself must not be folded into the advertised cap list.
+        let (first, first_raw) = msg(":irc.example.net CAP * LS * sasl");
+        assert!(neg.handle_server_line(&first, &first_raw).is_empty());
+
+        let (second, second_raw) = msg(":irc.example.net CAP * LS :away-notify");
+        let out = neg.handle_server_line(&second, &second_raw);
+
+        assert_eq!(1, out.len());
+        assert_eq!("CAP * LS :sasl away-notify", strify(&out[0]));
+    }
+
+    #[test]
+    fn test_server_ack_single_cap_no_colon_passes_through() {
+        let mut neg = CapNegotiator::new(CapPolicy::new());
+        let (message, raw) = msg(":irc.example.net CAP * ACK sasl");
+
+        // ACK isn't rewritten, so it should still pass through unchanged even though it hits the
+        // no-colon cap-list fallback on the way through `CapMessage::from_message`.
+        assert_eq!(vec![raw.clone()], neg.handle_server_line(&message, &raw));
+    }
+
+    #[test]
+    fn test_non_cap_message_passes_through() {
+        let mut neg = CapNegotiator::new(CapPolicy::new());
+        let (message, raw) = msg("PRIVMSG #chan :hello");
+
+        assert_eq!(vec![raw.clone()], neg.handle_server_line(&message, &raw));
+        assert_eq!((vec![raw.clone()], Vec::new()), neg.handle_client_line(&message, &raw));
+    }
+}
@@ -0,0 +1,132 @@
+//! Proxy configuration, loaded from a TOML file at startup.
+
+use std::collections::HashMap;
+use std::fs::File;
+use std::io;
+use std::io::Read;
+use std::path::Path;
+
+use cap::CapPolicy;
+
+/// Top-level proxy configuration.
+#[derive(Debug, Deserialize)]
+pub struct Config {
+    /// The address this proxy listens on for client connections.
+    pub listen: String,
+    /// Which entry of `upstreams` incoming connections are routed to.
+    pub default_upstream: String,
+    /// Named upstream servers this proxy knows how to dial.
+    pub upstreams: HashMap<String, Upstream>,
+    /// Capabilities to strip from CAP negotiation, by bare name, regardless of which side offers
+    /// or requests them.
+    #[serde(default)]
+    pub denied_caps: Vec<String>,
+}
+
+/// A single upstream IRC server.
+#[derive(Debug, Deserialize)]
+pub struct Upstream {
+    pub host: String,
+    pub port: u16,
+    /// Whether this upstream should be reached over TLS.
+    ///
+    /// Parsed and carried through, but not yet acted on: this proxy doesn't speak TLS yet.
+    #[serde(default)]
+    pub tls: bool,
+}
+
+impl Config {
+    /// Loads and parses a `Config` from the TOML file at `path`.
+    pub fn from_file<P: AsRef<Path>>(path: P) -> io::Result<Config> {
+        let mut file = File::open(path)?;
+        let mut contents = String::new();
+        file.read_to_string(&mut contents)?;
+
+        toml::from_str(&contents).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Looks up the configured default upstream.
+    pub fn default_upstream(&self) -> io::Result<&Upstream> {
+        self.upstreams.get(&self.default_upstream).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("no upstream named {:?} in config", self.default_upstream),
+            )
+        })
+    }
+
+    /// Builds the `CapPolicy` described by `denied_caps`.
+    pub fn cap_policy(&self) -> CapPolicy {
+        let mut policy = CapPolicy::new();
+
+        for cap in &self.denied_caps {
+            policy.deny(cap.clone().into_bytes());
+        }
+
+        policy
+    }
+}
+
+impl Upstream {
+    /// The `host:port` string this upstream should be dialed at.
+    pub fn addr(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_from_str_parses_config() {
+        let config: Config = toml::from_str(r#"
+            listen = "0.0.0.0:6667"
+            default_upstream = "freenode"
+            denied_caps = ["away-notify"]
+
+            [upstreams.freenode]
+            host = "chat.freenode.net"
+            port = 6667
+            tls = true
+        "#).unwrap();
+
+        assert_eq!("0.0.0.0:6667", config.listen);
+        assert_eq!("freenode", config.default_upstream);
+        assert_eq!(vec!["away-notify".to_string()], config.denied_caps);
+
+        let upstream = config.default_upstream().unwrap();
+        assert_eq!("chat.freenode.net:6667", upstream.addr());
+        assert!(upstream.tls);
+    }
+
+    #[test]
+    fn test_from_str_defaults_denied_caps_and_tls() {
+        let config: Config = toml::from_str(r#"
+            listen = "0.0.0.0:6667"
+            default_upstream = "freenode"
+
+            [upstreams.freenode]
+            host = "chat.freenode.net"
+            port = 6667
+        "#).unwrap();
+
+        assert!(config.denied_caps.is_empty());
+        assert!(!config.upstreams["freenode"].tls);
+    }
+
+    #[test]
+    fn test_default_upstream_not_found() {
+        let config: Config = toml::from_str(r#"
+            listen = "0.0.0.0:6667"
+            default_upstream = "freenode"
+
+            [upstreams.efnet]
+            host = "irc.efnet.net"
+            port = 6667
+        "#).unwrap();
+
+        let err = config.default_upstream().unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+    }
+}
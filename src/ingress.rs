@@ -0,0 +1,233 @@
+//! Line-framing ingress: turns a raw byte stream into a stream of parsed IRC messages.
+//!
+//! `Ingress` sits on the read half of a socket in the proxy's data path: it buffers incoming
+//! bytes, cuts out complete IRC lines, and hands each one to `irc::Message::parse`, so the
+//! forwarding logic works on message boundaries rather than raw bytes.
+
+use std::io;
+
+use bytes::{Bytes, BytesMut};
+
+use futures::{Async, Poll, Stream};
+
+use tokio_io::AsyncRead;
+
+use irc::Message;
+
+/// Default maximum line length, in bytes, not counting the line terminator.
+const DEFAULT_MAX_LINE: usize = 512;
+
+/// Maximum line length permitted when a line begins with an IRCv3 `@` tags segment, per the
+/// message-tags spec.
+const TAGGED_MAX_LINE: usize = 8191;
+
+/// Size of the chunks read off the underlying socket.
+const READ_SIZE: usize = 4096;
+
+/// A single complete line pulled off the wire, still attached to its parse result (if any).
+///
+/// Keeping `raw` around lets a consumer forward the original bytes unmodified, without having to
+/// re-serialize `message` for the common case where nothing about the line needs to change.
+pub struct Line {
+    pub raw: Bytes,
+    pub message: Option<Message>,
+}
+
+/// Accumulates bytes from an `AsyncRead` source and turns them into a stream of [`Line`]s.
+///
+/// Lines are terminated by `\r\n`, though a bare `\n` is tolerated too. A line longer than the
+/// configured maximum is dropped — the buffered bytes are discarded up to and including the next
+/// newline — rather than allowed to grow the buffer without bound.
+pub struct Ingress<R> {
+    r: R,
+    buf: BytesMut,
+    eof: bool,
+    /// Set once a line has been found to exceed the length limit before its terminator arrived.
+    /// While this is set, everything read is discarded (not handed out as a new line) until the
+    /// terminator that actually ends the oversized line is found.
+    dropping: bool,
+}
+
+impl<R> Ingress<R> {
+    /// Creates a new `Ingress` reading lines from `r`.
+    pub fn new(r: R) -> Ingress<R> {
+        Ingress {
+            r: r,
+            buf: BytesMut::new(),
+            eof: false,
+            dropping: false,
+        }
+    }
+
+    fn max_line_len(&self) -> usize {
+        match self.buf.get(0) {
+            Some(&b'@') => TAGGED_MAX_LINE,
+            _ => DEFAULT_MAX_LINE,
+        }
+    }
+
+    /// Pulls the next complete line out of the buffer, dropping (and skipping past) any line that
+    /// exceeds the configured maximum length — including one whose terminator hasn't arrived yet,
+    /// in which case every byte read until that terminator shows up is discarded too. Without
+    /// that, the tail of an oversized line (plus whatever follows it) could be read into the
+    /// now-emptied buffer and handed out as if it were a brand new, legitimate line.
+    fn take_line(&mut self) -> Option<Bytes> {
+        loop {
+            let nl = match self.buf.iter().position(|&b| b == b'\n') {
+                Some(nl) => nl,
+                None => {
+                    // a trailing `\r` may be the first half of a terminator split across reads,
+                    // so it doesn't count toward the line's length
+                    let pending = match self.buf.last() {
+                        Some(&b'\r') => self.buf.len() - 1,
+                        _ => self.buf.len(),
+                    };
+                    if !self.dropping && pending > self.max_line_len() {
+                        self.dropping = true;
+                    }
+                    if self.dropping {
+                        // keep the buffer from growing without bound while we wait for the
+                        // terminator of the line we're discarding.
+                        self.buf.clear();
+                    }
+                    return None;
+                }
+            };
+
+            if self.dropping {
+                // this terminator ends the line we've been discarding the whole way through
+                let _ = self.buf.split_to(nl + 1);
+                self.dropping = false;
+                continue;
+            }
+
+            let line_end = if nl > 0 && self.buf[nl - 1] == b'\r' { nl - 1 } else { nl };
+
+            if line_end > self.max_line_len() {
+                // the whole oversized line (and its terminator) arrived in one read: drop it
+                let _ = self.buf.split_to(nl + 1);
+                continue;
+            }
+
+            let line = self.buf.split_to(line_end).freeze();
+            let _ = self.buf.split_to(nl + 1 - line_end);
+            return Some(line);
+        }
+    }
+
+    fn fill_buf(&mut self) -> Poll<(), io::Error> where R: AsyncRead {
+        if self.eof {
+            return Ok(Async::Ready(()));
+        }
+
+        let mut chunk = [0u8; READ_SIZE];
+        let n = try_nb!(self.r.read(&mut chunk));
+
+        if n == 0 {
+            self.eof = true;
+        } else {
+            self.buf.extend_from_slice(&chunk[..n]);
+        }
+
+        Ok(Async::Ready(()))
+    }
+}
+
+impl<R: AsyncRead> Stream for Ingress<R> {
+    type Item = Line;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<Option<Line>, io::Error> {
+        loop {
+            if let Some(raw) = self.take_line() {
+                let message = Message::parse(raw.clone());
+                return Ok(Async::Ready(Some(Line { raw: raw, message: message })));
+            }
+
+            if self.eof {
+                return Ok(Async::Ready(None));
+            }
+
+            try_ready!(self.fill_buf());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn strify(s: &Bytes) -> &str { unsafe { ::std::str::from_utf8_unchecked(s) } }
+
+    fn push(ingress: &mut Ingress<()>, data: &[u8]) {
+        ingress.buf.extend_from_slice(data);
+    }
+
+    #[test]
+    fn test_take_line_basic() {
+        let mut ingress = Ingress::new(());
+        push(&mut ingress, b"PING 1\r\nPING 2\n");
+
+        assert_eq!("PING 1", strify(&ingress.take_line().unwrap()));
+        assert_eq!("PING 2", strify(&ingress.take_line().unwrap()));
+        assert!(ingress.take_line().is_none());
+    }
+
+    #[test]
+    fn test_take_line_partial_stays_buffered() {
+        let mut ingress = Ingress::new(());
+        push(&mut ingress, b"PING 1");
+
+        assert!(ingress.take_line().is_none());
+
+        push(&mut ingress, b"\r\n");
+        assert_eq!("PING 1", strify(&ingress.take_line().unwrap()));
+    }
+
+    #[test]
+    fn test_take_line_drops_oversized_line_found_whole() {
+        let mut ingress = Ingress::new(());
+        let long = vec![b'x'; DEFAULT_MAX_LINE + 1];
+
+        push(&mut ingress, &long);
+        push(&mut ingress, b"\r\n");
+        push(&mut ingress, b"PING ok\r\n");
+
+        assert_eq!("PING ok", strify(&ingress.take_line().unwrap()));
+    }
+
+    // Regression test: an oversized line with no terminator yet must not let its eventual
+    // terminator (and whatever follows it) be mistaken for the start of a new line.
+    #[test]
+    fn test_take_line_drops_oversized_line_split_across_reads() {
+        let mut ingress = Ingress::new(());
+
+        push(&mut ingress, &vec![b'x'; 1000]);
+        assert!(ingress.take_line().is_none());
+        assert!(ingress.dropping);
+
+        push(&mut ingress, b"INJECTED PRIVMSG #x :hi\r\n");
+        assert!(ingress.take_line().is_none());
+        assert!(!ingress.dropping);
+
+        push(&mut ingress, b"PING legit\r\n");
+        assert_eq!("PING legit", strify(&ingress.take_line().unwrap()));
+    }
+
+    #[test]
+    fn test_take_line_dropping_keeps_buffer_bounded_across_many_reads() {
+        let mut ingress = Ingress::new(());
+
+        push(&mut ingress, &vec![b'x'; 1000]);
+        assert!(ingress.take_line().is_none());
+
+        for _ in 0..10 {
+            push(&mut ingress, &vec![b'y'; 1000]);
+            assert!(ingress.take_line().is_none());
+            assert!(ingress.buf.is_empty());
+        }
+
+        push(&mut ingress, b"\r\nPING ok\r\n");
+        assert_eq!("PING ok", strify(&ingress.take_line().unwrap()));
+    }
+}
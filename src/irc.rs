@@ -1,6 +1,186 @@
 //! Tools for parsing IRC messages, designed to be as efficient and minimal as possible, while
 //! not compromising correctness.
 
+use bytes::Bytes;
+
+/// A fully parsed IRC message, backed by `bytes::Bytes` rather than a borrowed slice.
+///
+/// Every field here is a `Bytes` sub-slice of the buffer the message was parsed from, created
+/// with `Bytes::slice` rather than copied. That makes a `Message` cheap to clone (it's just a
+/// refcount bump per field) and, unlike a `&[u8]`-based parse, able to outlive the buffer it came
+/// from and be held across `await` points.
+#[derive(Debug, Clone)]
+pub struct Message {
+    /// The raw `key=value;key2=value2` tags segment, with the leading `@` stripped. See
+    /// `extract_tags` for turning this into actual key/value pairs.
+    pub tags: Option<Bytes>,
+    /// The message prefix, with the leading `:` stripped.
+    pub prefix: Option<Bytes>,
+    pub verb: Bytes,
+    pub params: Vec<Bytes>,
+    /// The final `:`-prefixed parameter (which may contain spaces), with the leading `:` stripped.
+    pub trailing: Option<Bytes>,
+}
+
+impl Message {
+    /// Parses a single IRC line (without a trailing `\r\n`) into a `Message`.
+    ///
+    /// Returns `None` if the line has no verb to speak of, which includes empty lines and lines
+    /// consisting only of tags and/or a prefix.
+    pub fn parse(line: Bytes) -> Option<Message> {
+        let mut i = 0;
+
+        while i < line.len() && line[i] == b' ' { i += 1; }
+
+        let tags = if i < line.len() && line[i] == b'@' {
+            i += 1;
+            let start = i;
+            while i < line.len() && line[i] != b' ' { i += 1; }
+            let tags = line.slice(start, i);
+            while i < line.len() && line[i] == b' ' { i += 1; }
+            Some(tags)
+        } else {
+            None
+        };
+
+        let prefix = if i < line.len() && line[i] == b':' {
+            i += 1;
+            let start = i;
+            while i < line.len() && line[i] != b' ' { i += 1; }
+            let prefix = line.slice(start, i);
+            while i < line.len() && line[i] == b' ' { i += 1; }
+            Some(prefix)
+        } else {
+            None
+        };
+
+        let verb_start = i;
+        while i < line.len() && line[i] != b' ' { i += 1; }
+        if verb_start == i {
+            return None;
+        }
+        let verb = line.slice(verb_start, i);
+
+        let mut params = Vec::new();
+        let mut trailing = None;
+
+        loop {
+            while i < line.len() && line[i] == b' ' { i += 1; }
+            if i >= line.len() { break; }
+
+            if line[i] == b':' {
+                trailing = Some(line.slice(i + 1, line.len()));
+                break;
+            }
+
+            let start = i;
+            while i < line.len() && line[i] != b' ' { i += 1; }
+            params.push(line.slice(start, i));
+        }
+
+        Some(Message { tags: tags, prefix: prefix, verb: verb, params: params, trailing: trailing })
+    }
+
+    /// Parses and unescapes this message's IRCv3 tags, if it has any.
+    ///
+    /// Keys are taken verbatim (they may carry a vendor prefix and `/`). Values are unescaped per
+    /// the IRCv3 tag spec, reusing the original buffer when a value contains no escapes, and
+    /// falling back to an owned buffer only for the values that actually need decoding.
+    pub fn tags(&self) -> Option<Vec<(Bytes, Bytes)>> {
+        self.tags.as_ref().map(|tags| parse_tag_segment(tags.clone()))
+    }
+}
+
+/// Extracts the leading IRCv3 tags segment of a raw line, if present, as key/value pairs.
+///
+/// If the first non-space byte of `line` is `@`, the segment up to the next space is split on
+/// `;`, and each item split once on `=` into a key and a raw value (a missing `=` means an empty
+/// value). See `parse_tag_segment` for how values are unescaped.
+///
+/// Returns `None` if `line` has no tags segment.
+pub fn extract_tags(line: Bytes) -> Option<Vec<(Bytes, Bytes)>> {
+    let mut i = 0;
+    while i < line.len() && line[i] == b' ' { i += 1; }
+
+    if i >= line.len() || line[i] != b'@' { return None; }
+    i += 1;
+
+    let start = i;
+    while i < line.len() && line[i] != b' ' { i += 1; }
+
+    Some(parse_tag_segment(line.slice(start, i)))
+}
+
+/// Splits an already-isolated `key=value;key2=value2` segment (no leading `@`, no spaces) into
+/// unescaped key/value pairs.
+fn parse_tag_segment(segment: Bytes) -> Vec<(Bytes, Bytes)> {
+    if segment.is_empty() {
+        // "@ PRIVMSG foo": a bare `@` with nothing after it is not one empty tag, it's no tags
+        return Vec::new();
+    }
+
+    let mut tags = Vec::new();
+    let mut i = 0;
+
+    loop {
+        let item_end = segment[i..].iter().position(|&b| b == b';').map(|p| i + p).unwrap_or(segment.len());
+        let item = segment.slice(i, item_end);
+
+        let key;
+        let raw_value;
+        match item.iter().position(|&b| b == b'=') {
+            Some(eq) => {
+                key = item.slice(0, eq);
+                raw_value = item.slice(eq + 1, item.len());
+            }
+            None => {
+                key = item.clone();
+                raw_value = Bytes::new();
+            }
+        }
+
+        tags.push((key, unescape_tag_value(raw_value)));
+
+        if item_end >= segment.len() { break; }
+        i = item_end + 1;
+    }
+
+    tags
+}
+
+/// Unescapes an IRCv3 tag value: `\:` becomes `;`, `\s` becomes a space, `\\` becomes `\`, `\r`
+/// and `\n` become CR and LF, an escaped character not in that list is taken verbatim, and a
+/// trailing lone backslash is dropped.
+///
+/// Returns a clone of `raw` (a cheap refcount bump, not a copy) when it contains no escapes.
+fn unescape_tag_value(raw: Bytes) -> Bytes {
+    if !raw.iter().any(|&b| b == b'\\') {
+        return raw;
+    }
+
+    let mut out = Vec::with_capacity(raw.len());
+    let mut bytes = raw.iter().cloned();
+
+    while let Some(b) = bytes.next() {
+        if b != b'\\' {
+            out.push(b);
+            continue;
+        }
+
+        match bytes.next() {
+            Some(b':') => out.push(b';'),
+            Some(b's') => out.push(b' '),
+            Some(b'\\') => out.push(b'\\'),
+            Some(b'r') => out.push(b'\r'),
+            Some(b'n') => out.push(b'\n'),
+            Some(other) => out.push(other),
+            None => {} // trailing lone backslash: dropped
+        }
+    }
+
+    Bytes::from(out)
+}
+
 /// Extracts the verb part of an IRC message.
 ///
 /// If the return value represents a meaningful IRC verb, then the input is either well-formed, or
@@ -154,6 +334,88 @@ mod tests {
 
     fn strify(s: &[u8]) -> &str { unsafe { ::std::str::from_utf8_unchecked(s) } }
 
+    #[test]
+    fn test_message_parse_full() {
+        let line = Bytes::from_static(b"@id=123;time=2021-01-01T00:00:00Z :nick!user@host PRIVMSG #chan arg :trailing part");
+        let msg = Message::parse(line).unwrap();
+
+        assert_eq!("id=123;time=2021-01-01T00:00:00Z", strify(&msg.tags.unwrap()));
+        assert_eq!("nick!user@host", strify(&msg.prefix.unwrap()));
+        assert_eq!("PRIVMSG", strify(&msg.verb));
+        assert_eq!(vec!["#chan", "arg"], msg.params.iter().map(|p| strify(p)).collect::<Vec<_>>());
+        assert_eq!("trailing part", strify(&msg.trailing.unwrap()));
+    }
+
+    #[test]
+    fn test_message_parse_minimal() {
+        let msg = Message::parse(Bytes::from_static(b"PING")).unwrap();
+
+        assert!(msg.tags.is_none());
+        assert!(msg.prefix.is_none());
+        assert_eq!("PING", strify(&msg.verb));
+        assert!(msg.params.is_empty());
+        assert!(msg.trailing.is_none());
+    }
+
+    #[test]
+    fn test_message_parse_no_verb() {
+        assert!(Message::parse(Bytes::from_static(b"")).is_none());
+        assert!(Message::parse(Bytes::from_static(b"  ")).is_none());
+        assert!(Message::parse(Bytes::from_static(b":server   ")).is_none());
+    }
+
+    #[test]
+    fn test_extract_tags_no_escapes() {
+        let tags = extract_tags(Bytes::from_static(b"@id=123;account=jilles;solanum.chat/ip=1.2.3.4 PRIVMSG #chan :hi")).unwrap();
+
+        assert_eq!(tags.len(), 3);
+        assert_eq!(("id", "123"), (strify(&tags[0].0), strify(&tags[0].1)));
+        assert_eq!(("account", "jilles"), (strify(&tags[1].0), strify(&tags[1].1)));
+        assert_eq!(("solanum.chat/ip", "1.2.3.4"), (strify(&tags[2].0), strify(&tags[2].1)));
+    }
+
+    #[test]
+    fn test_extract_tags_missing_value() {
+        let tags = extract_tags(Bytes::from_static(b"@solo;key=value PRIVMSG #chan :hi")).unwrap();
+
+        assert_eq!(("solo", ""), (strify(&tags[0].0), strify(&tags[0].1)));
+        assert_eq!(("key", "value"), (strify(&tags[1].0), strify(&tags[1].1)));
+    }
+
+    #[test]
+    fn test_extract_tags_unescapes_values() {
+        let tags = extract_tags(Bytes::from_static(br"@msg=hi\sthere\:pal\\\r\nbye\x PRIVMSG #chan :hi")).unwrap();
+
+        assert_eq!(1, tags.len());
+        assert_eq!("msg", strify(&tags[0].0));
+        assert_eq!(b"hi there;pal\\\r\nbyex".to_vec(), tags[0].1.to_vec());
+    }
+
+    #[test]
+    fn test_extract_tags_trailing_backslash_dropped() {
+        let tags = extract_tags(Bytes::from_static(br"@k=a\ PRIVMSG #chan :hi")).unwrap();
+        assert_eq!("a", strify(&tags[0].1));
+    }
+
+    #[test]
+    fn test_extract_tags_empty_segment_yields_no_tags() {
+        let tags = extract_tags(Bytes::from_static(b"@ PRIVMSG foo")).unwrap();
+        assert!(tags.is_empty());
+    }
+
+    #[test]
+    fn test_extract_tags_absent() {
+        assert!(extract_tags(Bytes::from_static(b":server PRIVMSG #chan :hi")).is_none());
+    }
+
+    #[test]
+    fn test_message_tags_accessor() {
+        let msg = Message::parse(Bytes::from_static(b"@id=123 PRIVMSG #chan :hi")).unwrap();
+        let tags = msg.tags().unwrap();
+
+        assert_eq!(("id", "123"), (strify(&tags[0].0), strify(&tags[0].1)));
+    }
+
     #[test]
     fn test_extract_verb() {
         let inputs = {
@@ -1,90 +1,88 @@
 extern crate badlog;
 extern crate bytes;
 extern crate futures;
+extern crate serde;
 extern crate tokio_io;
+extern crate toml;
 
 #[macro_use]
 extern crate log;
 #[macro_use]
+extern crate serde_derive;
+#[macro_use]
 extern crate tokio_core;
 
-mod splice;
+mod cap;
+mod config;
+mod ingress;
+mod irc;
+mod proxy;
 
-use std::io;
+use std::env;
+use std::rc::Rc;
 
-use futures::Async;
 use futures::Future;
-use futures::Poll;
 use futures::Stream;
 
-use tokio_io::AsyncRead;
-
-use splice::Splicer;
-
-struct IoTask<T> {
-    task: T
-}
-
-impl<T> IoTask<T> {
-    fn new(task: T) -> IoTask<T> {
-        IoTask { task: task }
-    }
-}
-
-impl<T> Future for IoTask<T> where T: Future<Error=io::Error> {
-    type Item = T::Item;
-    type Error = ();
-
-    fn poll(&mut self) -> Poll<T::Item, ()> {
-        match self.task.poll() {
-            Ok(Async::NotReady) => {
-                Ok(Async::NotReady)
-            },
-
-            Ok(Async::Ready(x)) => {
-                info!("an IO task finished");
-                Ok(Async::Ready(x))
-            },
-
-            Err(e) => {
-                warn!("an IO task errored: {}", e);
-                Err(())
-            }
-        }
-    }
-}
+use config::Config;
 
 fn main() {
-    use std::cell::RefCell;
-
     use tokio_core::net::TcpListener;
     use tokio_core::net::TcpStream;
     use tokio_core::reactor::Core;
 
     badlog::init_from_env("LOG");
 
+    let config_path = env::args().nth(1).unwrap_or_else(|| "oxide-proxy.toml".to_string());
+    let config = Rc::new(Config::from_file(&config_path).expect("could not load config"));
+
     let mut core = Core::new().expect("could not create tokio reactor");
     let handle = core.handle();
 
-    let addr = "127.0.0.1:6667".parse().unwrap();
+    let addr = config.listen.parse().expect("invalid listen address in config");
     let listener = TcpListener::bind(&addr, &handle).unwrap();
 
-    let prev: RefCell<Option<TcpStream>> = RefCell::new(None);
+    let server = {
+        let handle = handle.clone();
+        let config = config.clone();
+
+        listener.incoming().for_each(move |(client, peer)| {
+            info!("accepted connection from {}", peer);
+
+            let upstream = match config.default_upstream() {
+                Ok(upstream) => upstream,
+                Err(e) => {
+                    warn!("not accepting connection from {}: {}", peer, e);
+                    return Ok(());
+                }
+            };
+
+            if upstream.tls {
+                warn!("upstream {:?} asks for tls, which this proxy doesn't support yet; \
+                       connecting in plaintext", config.default_upstream);
+            }
+
+            let upstream_addr = match upstream.addr().parse() {
+                Ok(addr) => addr,
+                Err(e) => {
+                    warn!("invalid upstream address {:?}: {}", upstream.addr(), e);
+                    return Ok(());
+                }
+            };
+
+            let spawn_handle = handle.clone();
+            let policy = config.cap_policy();
 
-    let server = listener.incoming().for_each(|(sock, _)| {
-        let mut p = prev.borrow_mut();
+            let dial = TcpStream::connect(&upstream_addr, &handle).and_then(move |upstream_sock| {
+                proxy::spawn(&spawn_handle, client, upstream_sock, policy);
+                Ok(())
+            });
 
-        if let Some(p) = p.take() {
-            let (ar, aw) = p.split();
-            let (br, bw) = sock.split();
-            handle.spawn(IoTask::new(Splicer::new(ar, bw)));
-            handle.spawn(IoTask::new(Splicer::new(br, aw)));
-        } else {
-            *p = Some(sock);
-        }
+            handle.spawn(dial.map_err(|e| warn!("could not dial upstream: {}", e)));
 
-        Ok(())
-    });
+            Ok(())
+        })
+    };
 
     core.run(server).expect("core exited");
 }
@@ -0,0 +1,151 @@
+//! Wires `Ingress` and `CapNegotiator` into the live data path between a client and its upstream.
+//!
+//! `main`'s accept loop hands each client/upstream socket pair to `spawn`, which runs two
+//! line-framed forwarding tasks that share one `CapNegotiator`, so CAP negotiation is mediated
+//! rather than tunneled blind.
+
+use std::cell::RefCell;
+use std::io;
+use std::rc::Rc;
+
+use bytes::Bytes;
+
+use futures::Async;
+use futures::Future;
+use futures::Poll;
+use futures::Stream;
+use futures::sync::mpsc;
+
+use tokio_core::net::TcpStream;
+use tokio_core::reactor::Handle;
+
+use tokio_io::AsyncRead;
+use tokio_io::AsyncWrite;
+use tokio_io::io::write_all;
+
+use cap::CapNegotiator;
+use cap::CapPolicy;
+use ingress::Ingress;
+
+/// An item queued for a `writer` task: either a line to write out, or a signal that the source
+/// feeding it has finished (or failed), so the write half should be half-closed.
+enum Frame {
+    Line(Bytes),
+    Shutdown,
+}
+
+/// Spawns the tasks that proxy a single client/upstream connection pair, applying `policy` to CAP
+/// negotiation along the way. Anything other than a `CAP` message this negotiator cares about is
+/// forwarded unmodified.
+///
+/// Each direction shuts down the write half of the socket it forwards to once its own read side
+/// reaches EOF or errors out, so that a client or upstream disconnect propagates to the other
+/// side of the connection.
+pub fn spawn(handle: &Handle, client: TcpStream, upstream: TcpStream, policy: CapPolicy) {
+    let negotiator = Rc::new(RefCell::new(CapNegotiator::new(policy)));
+
+    let (client_r, client_w) = client.split();
+    let (upstream_r, upstream_w) = upstream.split();
+
+    let (client_tx, client_rx) = mpsc::unbounded();
+    let (upstream_tx, upstream_rx) = mpsc::unbounded();
+
+    handle.spawn(writer(client_w, client_rx));
+    handle.spawn(writer(upstream_w, upstream_rx));
+
+    {
+        let negotiator = negotiator.clone();
+        let reply_tx = client_tx.clone();
+        let forward_tx = upstream_tx.clone();
+        let done_tx = upstream_tx.clone();
+
+        handle.spawn(Ingress::new(client_r).for_each(move |line| {
+            match line.message {
+                Some(msg) => {
+                    let (forward, reply) = negotiator.borrow_mut().handle_client_line(&msg, &line.raw);
+                    send_lines(&forward_tx, forward);
+                    send_lines(&reply_tx, reply);
+                }
+                None => send_lines(&forward_tx, vec![line.raw]),
+            }
+
+            Ok(())
+        }).then(move |result| {
+            if let Err(e) = result {
+                warn!("client ingress error: {}", e);
+            }
+            send_shutdown(&done_tx);
+            Ok(())
+        }));
+    }
+
+    let forward_tx = client_tx.clone();
+    let done_tx = client_tx;
+
+    handle.spawn(Ingress::new(upstream_r).for_each(move |line| {
+        match line.message {
+            Some(msg) => {
+                let forward = negotiator.borrow_mut().handle_server_line(&msg, &line.raw);
+                send_lines(&forward_tx, forward);
+            }
+            None => send_lines(&forward_tx, vec![line.raw]),
+        }
+
+        Ok(())
+    }).then(move |result| {
+        if let Err(e) = result {
+            warn!("upstream ingress error: {}", e);
+        }
+        send_shutdown(&done_tx);
+        Ok(())
+    }));
+}
+
+/// Queues `lines` to be written out, each followed by `\r\n`.
+fn send_lines(tx: &mpsc::UnboundedSender<Frame>, lines: Vec<Bytes>) {
+    for line in lines {
+        let mut framed = Vec::with_capacity(line.len() + 2);
+        framed.extend_from_slice(&line);
+        framed.extend_from_slice(b"\r\n");
+
+        if tx.unbounded_send(Frame::Line(Bytes::from(framed))).is_err() {
+            warn!("dropped a line: the other side of this connection is already gone");
+        }
+    }
+}
+
+/// Queues a half-close of the writer this `tx` feeds.
+fn send_shutdown(tx: &mpsc::UnboundedSender<Frame>) {
+    let _ = tx.unbounded_send(Frame::Shutdown);
+}
+
+/// Drains `rx`, writing each line out to `w` in order, and shutting `w` down when told to.
+fn writer<W>(w: W, rx: mpsc::UnboundedReceiver<Frame>) -> Box<Future<Item = (), Error = ()>>
+    where W: AsyncWrite + 'static
+{
+    let fut = rx
+        .map_err(|_| io::Error::new(io::ErrorKind::Other, "proxy channel closed"))
+        .fold(w, |w, frame| -> Box<Future<Item = W, Error = io::Error>> {
+            match frame {
+                Frame::Line(line) => Box::new(write_all(w, line).map(|(w, _)| w)),
+                Frame::Shutdown => Box::new(Shutdown(Some(w))),
+            }
+        })
+        .map(|_| ())
+        .map_err(|e| warn!("proxy writer error: {}", e));
+
+    Box::new(fut)
+}
+
+/// A future that drives `AsyncWrite::shutdown` to completion, then hands the writer back.
+struct Shutdown<W>(Option<W>);
+
+impl<W: AsyncWrite> Future for Shutdown<W> {
+    type Item = W;
+    type Error = io::Error;
+
+    fn poll(&mut self) -> Poll<W, io::Error> {
+        try_ready!(self.0.as_mut().expect("polled Shutdown after completion").shutdown());
+        Ok(Async::Ready(self.0.take().unwrap()))
+    }
+}